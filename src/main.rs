@@ -1,10 +1,16 @@
 #![warn(clippy::pedantic)]
 
 use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
 
 const SCREEN_WIDTH: i32 = 80;
 const SCREEN_HEIGHT: i32 = 50;
 const FRAME_DURATION: f32 = 40.0;
+const MAX_HIGH_SCORES: usize = 5;
+const HIGH_SCORE_FILE: &str = "highscores.json";
+const ENDLESS_HIGH_SCORE_FILE: &str = "highscores_endless.json";
+const JUMP_IMPULSE: f32 = -2.0;
+const ENDLESS_LABELS: [&str; 5] = ["DANGER", "SPIKES", "ROCK", "NOPE", "WALL"];
 
 const DRAGON_FRAMES: [u16; 6] = [64, 1, 2, 3, 2, 1];
 
@@ -13,6 +19,27 @@ const DRAGON_FRAMES: [u16; 6] = [64, 1, 2, 3, 2, 1];
 // |    0,2  1,2  2,2  3,2  4,2
 // y __________________________> x
 
+/// What happened between an entity and the player this frame.
+enum EntityEvent {
+    None,
+    Scored,
+    Killed,
+}
+
+// Common behaviour for anything that needs to be advanced and drawn each
+// frame, so `State` can hold a single `Vec<Box<dyn GameEntity>>` and iterate
+// it uniformly, regardless of concrete type.
+trait GameEntity {
+    fn tick(&mut self, ctx: &mut BTerm, world_x: i32);
+    fn render(&mut self, ctx: &mut BTerm, player_x: i32);
+
+    // Entities that don't interact with the player (e.g. the player itself)
+    // keep this default of doing nothing.
+    fn interact(&mut self, _player: &Player) -> EntityEvent {
+        EntityEvent::None
+    }
+}
+
 struct Player {
     x: i32,
     y: f32,
@@ -62,6 +89,50 @@ impl Player {
     fn flap(&mut self) {
         self.velocity = -1.0; // a negative number, so it moves upward, because 0 is the top of the screen
     }
+
+    fn jump(&mut self, impulse: f32) {
+        self.velocity = impulse;
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect {
+            x0: self.x,
+            y0: self.y as i32,
+            x1: self.x + 2,
+            y1: self.y as i32 + 2,
+        }
+    }
+}
+
+impl GameEntity for Player {
+    fn tick(&mut self, _ctx: &mut BTerm, _world_x: i32) {
+        self.gravity_and_mode();
+    }
+
+    fn render(&mut self, ctx: &mut BTerm, _player_x: i32) {
+        self.render(ctx);
+    }
+}
+
+struct Rect {
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+}
+
+impl Rect {
+    fn collides(&self, other: &Rect) -> bool {
+        self.x0 < other.x1 && self.x1 > other.x0 && self.y0 < other.y1 && self.y1 > other.y0
+    }
+}
+
+// Shared by both play modes so the floor shows up whether the player is
+// dodging wall obstacles or text obstacles.
+fn draw_ground(ctx: &mut BTerm) {
+    for x in 0..SCREEN_WIDTH {
+        ctx.set(x, SCREEN_HEIGHT - 1, WHITE, WHITE, to_cp437('#'));
+    }
 }
 
 struct Obstacle {
@@ -71,21 +142,16 @@ struct Obstacle {
 }
 
 impl Obstacle {
-    fn new(x: i32) -> Self {
+    fn new(x: i32, score: i32) -> Self {
         let mut random = RandomNumberGenerator::new();
         Obstacle {
             x,
             gap_y: random.range(5, 45),
-            size: i32::max(10, 40),
+            size: i32::max(2, 20 - score),
         }
     }
 
     fn render(&mut self, ctx: &mut BTerm, player_x: i32) {
-        // The ground
-        for x in 0..SCREEN_WIDTH {
-            ctx.set(x, SCREEN_HEIGHT - 1, WHITE, WHITE, to_cp437('#'));
-        }
-
         let screen_x = self.x - player_x;
         let half_size = self.size / 2;
 
@@ -102,16 +168,172 @@ impl Obstacle {
 
     fn hit_obstacle(&self, player: &Player) -> bool {
         let half_size = self.size / 2;
-        let does_x_match = player.x == self.x;
-        let player_above_gap = (player.y as i32) < self.gap_y - half_size;
-        let player_below_gap = (player.y as i32) > self.gap_y + half_size;
-        does_x_match && (player_above_gap || player_below_gap)
+        let player_box = player.bounds();
+        let top_wall = Rect {
+            x0: self.x,
+            y0: 0,
+            x1: self.x + 1,
+            y1: self.gap_y - half_size,
+        };
+        let bottom_wall = Rect {
+            x0: self.x,
+            y0: self.gap_y + half_size,
+            x1: self.x + 1,
+            y1: SCREEN_HEIGHT - 1,
+        };
+        player_box.collides(&top_wall) || player_box.collides(&bottom_wall)
+    }
+}
+
+impl GameEntity for Obstacle {
+    fn tick(&mut self, _ctx: &mut BTerm, _world_x: i32) {
+        // Obstacles don't move on their own; they only scroll relative to
+        // the player, which is handled in `render`.
+    }
+
+    fn render(&mut self, ctx: &mut BTerm, player_x: i32) {
+        self.render(ctx, player_x);
+    }
+
+    fn interact(&mut self, player: &Player) -> EntityEvent {
+        if self.hit_obstacle(player) {
+            EntityEvent::Killed
+        } else if player.x > self.x {
+            EntityEvent::Scored
+        } else {
+            EntityEvent::None
+        }
+    }
+}
+
+struct Coin {
+    x: i32,
+    anchor_y: f32,
+    y: f32,
+    bob: f32,
+}
+
+impl Coin {
+    fn new(x: i32, y: i32) -> Self {
+        Coin {
+            x,
+            anchor_y: y as f32,
+            y: y as f32,
+            bob: 0.0,
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect {
+            x0: self.x,
+            y0: self.y as i32,
+            x1: self.x + 1,
+            y1: self.y as i32 + 1,
+        }
+    }
+}
+
+impl GameEntity for Coin {
+    fn tick(&mut self, _ctx: &mut BTerm, _world_x: i32) {
+        self.bob += 0.1;
+        self.y = self.anchor_y + self.bob.sin() * 3.0;
+    }
+
+    fn render(&mut self, ctx: &mut BTerm, player_x: i32) {
+        let screen_x = self.x - player_x;
+        if (0..SCREEN_WIDTH).contains(&screen_x) {
+            ctx.set(screen_x, self.y as i32, GOLD, NAVY, to_cp437('*'));
+        }
+    }
+
+    fn interact(&mut self, player: &Player) -> EntityEvent {
+        if player.bounds().collides(&self.bounds()) {
+            EntityEvent::Scored
+        } else {
+            EntityEvent::None
+        }
+    }
+}
+
+struct TextObstacle {
+    x: i32,
+    y: i32,
+    label: &'static str,
+}
+
+impl TextObstacle {
+    fn new(x: i32, y: i32, label: &'static str) -> Self {
+        TextObstacle { x, y, label }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect {
+            x0: self.x,
+            y0: self.y,
+            x1: self.x + i32::try_from(self.label.len()).unwrap_or(i32::MAX),
+            y1: self.y + 1,
+        }
+    }
+}
+
+impl GameEntity for TextObstacle {
+    fn tick(&mut self, _ctx: &mut BTerm, _world_x: i32) {
+        // Text obstacles only scroll relative to the player; see `render`.
+    }
+
+    fn render(&mut self, ctx: &mut BTerm, player_x: i32) {
+        let screen_x = self.x - player_x;
+        let label_len = i32::try_from(self.label.len()).unwrap_or(i32::MAX);
+        if screen_x + label_len >= 0 && screen_x < SCREEN_WIDTH {
+            ctx.print(screen_x, self.y, self.label);
+        }
+    }
+
+    fn interact(&mut self, player: &Player) -> EntityEvent {
+        if player.bounds().collides(&self.bounds()) {
+            EntityEvent::Killed
+        } else if player.x >= self.bounds().x1 {
+            EntityEvent::Scored
+        } else {
+            EntityEvent::None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct HighScores(Vec<i32>);
+
+impl HighScores {
+    fn load(path: &str) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &str) {
+        if let Ok(contents) = serde_json::to_string(self) {
+            let _ = std::fs::write(path, contents);
+        }
+    }
+
+    fn insert(&mut self, score: i32, path: &str) {
+        self.0.push(score);
+        self.0.sort_unstable_by(|a, b| b.cmp(a));
+        self.0.truncate(MAX_HIGH_SCORES);
+        self.save(path);
+    }
+
+    fn top(&self) -> &[i32] {
+        &self.0
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum GameMode {
     Menu,
     Playing,
+    Endless,
     Pause,
     End,
 }
@@ -119,9 +341,13 @@ enum GameMode {
 struct State {
     player: Player,
     frame_time: f32,
-    obstacles: Vec<Obstacle>,
+    entities: Vec<Box<dyn GameEntity>>,
     mode: GameMode,
+    last_mode: GameMode,
     score: i32,
+    high_scores: HighScores,
+    endless_high_scores: HighScores,
+    score_recorded: bool,
 }
 
 impl State {
@@ -129,18 +355,54 @@ impl State {
         State {
             player: Player::new(5, 25),
             frame_time: 0.0,
-            obstacles: vec![Obstacle::new(SCREEN_WIDTH)],
+            entities: vec![Box::new(Obstacle::new(SCREEN_WIDTH, 0))],
             mode: GameMode::Menu,
+            last_mode: GameMode::Playing,
             score: 0,
+            high_scores: HighScores::load(HIGH_SCORE_FILE),
+            endless_high_scores: HighScores::load(ENDLESS_HIGH_SCORE_FILE),
+            score_recorded: false,
         }
     }
 
+    fn frame_duration(&self) -> f32 {
+        f32::max(15.0, FRAME_DURATION - self.score as f32)
+    }
+
+    // Ticks and renders every entity, resolves its interaction with the
+    // player, drops any that scored, and reports whether any of them killed
+    // the player this frame. Adding a new `GameEntity` impl never requires
+    // touching `play`/`play_endless` beyond how it gets spawned.
+    fn update_entities(&mut self, ctx: &mut BTerm, player_x: i32) -> bool {
+        let mut killed = false;
+        let mut scored = 0;
+        let player = &self.player;
+        self.entities.retain_mut(|entity| {
+            entity.tick(ctx, player_x);
+            entity.render(ctx, player_x);
+            match entity.interact(player) {
+                EntityEvent::Scored => {
+                    scored += 1;
+                    false
+                }
+                EntityEvent::Killed => {
+                    killed = true;
+                    true
+                }
+                EntityEvent::None => true,
+            }
+        });
+        self.score += scored;
+        killed
+    }
+
     fn play(&mut self, ctx: &mut BTerm) {
         ctx.cls_bg(NAVY);
         self.frame_time += ctx.frame_time_ms;
-        if self.frame_time > FRAME_DURATION {
+        if self.frame_time > self.frame_duration() {
             self.frame_time = 0.0;
-            self.player.gravity_and_mode();
+            let world_x = self.player.x;
+            self.player.tick(ctx, world_x);
         }
 
         match ctx.key {
@@ -150,11 +412,14 @@ impl State {
             Some(VirtualKeyCode::Escape) => {
                 // TODO implement pause
                 self.mode = GameMode::Pause;
+                self.last_mode = GameMode::Playing;
             }
             _ => {}
         }
 
-        self.player.render(ctx);
+        let player_x = self.player.x;
+        draw_ground(ctx);
+        GameEntity::render(&mut self.player, ctx, player_x);
         ctx.print(0, 0, "Press SPACE to flap.");
         ctx.print(0, 1, "Press ESC to pause.");
         ctx.print(0, 2, &format!("Score {}", self.score));
@@ -162,57 +427,116 @@ impl State {
         // add new obstacles with a certain percentage
         let mut random = RandomNumberGenerator::new();
         if self.frame_time as i32 % 50 == 0 && random.range(1, 40) % 10 == 0 {
-            self.obstacles
-                .push(Obstacle::new(self.player.x + SCREEN_WIDTH));
+            self.entities
+                .push(Box::new(Obstacle::new(self.player.x + SCREEN_WIDTH, self.score)));
+            if random.range(0, 2) == 0 {
+                self.entities.push(Box::new(Coin::new(
+                    self.player.x + SCREEN_WIDTH,
+                    random.range(5, 45),
+                )));
+            }
         }
 
-        // render obstacles
-        for obstacle in &mut self.obstacles {
-            obstacle.render(ctx, self.player.x);
+        let killed = self.update_entities(ctx, player_x);
+        if killed || self.player.y as i32 > SCREEN_HEIGHT {
+            self.mode = GameMode::End;
+            self.last_mode = GameMode::Playing;
         }
+    }
 
-        // add obstacles to remove in a vec
-        let mut pop_obstacle: bool = false;
-        for obstacle in &mut self.obstacles {
-            if self.player.x > obstacle.x {
-                self.score += 1;
-                pop_obstacle = true;
-            }
+    fn play_endless(&mut self, ctx: &mut BTerm) {
+        ctx.cls_bg(NAVY);
+        self.frame_time += ctx.frame_time_ms;
+        if self.frame_time > self.frame_duration() {
+            self.frame_time = 0.0;
+            let world_x = self.player.x;
+            self.player.tick(ctx, world_x);
         }
 
-        for obstacle in &mut self.obstacles {
-            if self.player.y as i32 > SCREEN_HEIGHT || obstacle.hit_obstacle(&self.player) {
-                self.mode = GameMode::End;
+        match ctx.key {
+            Some(VirtualKeyCode::Space) => {
+                self.player.jump(JUMP_IMPULSE);
+            }
+            Some(VirtualKeyCode::Escape) => {
+                self.mode = GameMode::Pause;
+                self.last_mode = GameMode::Endless;
             }
+            _ => {}
+        }
+
+        let player_x = self.player.x;
+        draw_ground(ctx);
+        GameEntity::render(&mut self.player, ctx, player_x);
+        ctx.print(0, 0, "Press SPACE to jump.");
+        ctx.print(0, 1, "Press ESC to pause.");
+        ctx.print(0, 2, &format!("Score {}", self.score));
+
+        // add new text obstacles with a certain percentage
+        let mut random = RandomNumberGenerator::new();
+        if self.frame_time as i32 % 50 == 0 && random.range(1, 40) % 10 == 0 {
+            let labels_len = i32::try_from(ENDLESS_LABELS.len()).unwrap_or(i32::MAX);
+            let label_idx = usize::try_from(random.range(0, labels_len)).unwrap_or(0);
+            let label = ENDLESS_LABELS[label_idx];
+            self.entities.push(Box::new(TextObstacle::new(
+                self.player.x + SCREEN_WIDTH,
+                random.range(5, SCREEN_HEIGHT - 1),
+                label,
+            )));
         }
 
-        if pop_obstacle {
-            // remove first element
-            self.obstacles.remove(0);
+        let killed = self.update_entities(ctx, player_x);
+        if killed || self.player.y as i32 > SCREEN_HEIGHT {
+            self.mode = GameMode::End;
+            self.last_mode = GameMode::Endless;
         }
     }
 
     fn restart(&mut self) {
         self.player = Player::new(5, 25);
         self.frame_time = 0.0;
-        self.obstacles = vec![Obstacle::new(SCREEN_WIDTH)];
+        self.entities = vec![Box::new(Obstacle::new(SCREEN_WIDTH, 0))];
         self.mode = GameMode::Playing;
         self.score = 0;
+        self.score_recorded = false;
+    }
+
+    fn start_endless(&mut self) {
+        self.player = Player::new(5, 25);
+        self.frame_time = 0.0;
+        self.entities = vec![Box::new(TextObstacle::new(
+            SCREEN_WIDTH,
+            25,
+            ENDLESS_LABELS[0],
+        ))];
+        self.mode = GameMode::Endless;
+        self.score = 0;
+        self.score_recorded = false;
     }
 
     fn continue_game(&mut self) {
-        self.mode = GameMode::Playing;
+        self.mode = self.last_mode;
+    }
+
+    fn print_high_scores(ctx: &mut BTerm, y: i32, high_scores: &HighScores) {
+        ctx.print_centered(y, "High Scores");
+        for (rank, score) in high_scores.top().iter().enumerate() {
+            let rank = i32::try_from(rank).unwrap_or(i32::MAX);
+            ctx.print_centered(y + 1 + rank, format!("{}. {}", rank + 1, score));
+        }
     }
 
     fn main_menu(&mut self, ctx: &mut BTerm) {
         ctx.cls();
         ctx.print_centered(5, "Welcome to Flappy Dragon");
         ctx.print_centered(8, "(P) Play Game");
-        ctx.print_centered(9, "(Q) Quit Game");
+        ctx.print_centered(9, "(E) Endless Mode");
+        ctx.print_centered(10, "(Q) Quit Game");
+        Self::print_high_scores(ctx, 13, &self.high_scores);
 
         if let Some(key) = ctx.key {
             match key {
                 VirtualKeyCode::P => self.restart(),
+                VirtualKeyCode::E => self.start_endless(),
                 VirtualKeyCode::Q => ctx.quitting = true,
                 _ => {}
             }
@@ -235,15 +559,33 @@ impl State {
     }
 
     fn dead(&mut self, ctx: &mut BTerm) {
+        if !self.score_recorded {
+            match self.last_mode {
+                GameMode::Endless => self
+                    .endless_high_scores
+                    .insert(self.score, ENDLESS_HIGH_SCORE_FILE),
+                _ => self.high_scores.insert(self.score, HIGH_SCORE_FILE),
+            }
+            self.score_recorded = true;
+        }
+
         ctx.cls();
         ctx.print_centered(5, "You are dead!");
         ctx.print_centered(6, &format!("You earned {} points", self.score));
         ctx.print_centered(8, "(P) Play Game");
         ctx.print_centered(9, "(Q) Quit Game");
+        let high_scores = match self.last_mode {
+            GameMode::Endless => &self.endless_high_scores,
+            _ => &self.high_scores,
+        };
+        Self::print_high_scores(ctx, 12, high_scores);
 
         if let Some(key) = ctx.key {
             match key {
-                VirtualKeyCode::P => self.restart(),
+                VirtualKeyCode::P => match self.last_mode {
+                    GameMode::Endless => self.start_endless(),
+                    _ => self.restart(),
+                },
                 VirtualKeyCode::Q => ctx.quitting = true,
                 _ => {}
             }
@@ -257,6 +599,7 @@ impl GameState for State {
             GameMode::Menu => self.main_menu(ctx),
             GameMode::End => self.dead(ctx),
             GameMode::Playing => self.play(ctx),
+            GameMode::Endless => self.play_endless(ctx),
             GameMode::Pause => self.pause_menu(ctx),
         }
     }